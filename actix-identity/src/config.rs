@@ -0,0 +1,182 @@
+//! Configuration options to tune the behaviour of [`IdentityMiddleware`].
+//!
+//! [`IdentityMiddleware`]: crate::IdentityMiddleware
+
+use std::{sync::Arc, time::Duration};
+
+use crate::{
+    expiry::OnIdentityExpired,
+    middleware::IdentityMiddleware,
+    policy::{IdentityPolicy, SessionIdentityPolicy},
+};
+
+/// Determines how [`Identity::logout`] affects the current session.
+///
+/// [`Identity::logout`]: crate::Identity::logout
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LogoutBehaviour {
+    /// Purge the whole session, removing every entry — not just the identity keys.
+    PurgeSession,
+
+    /// Remove only the identity keys, leaving the rest of the session intact.
+    DeleteIdentityKeys,
+}
+
+#[derive(Clone)]
+pub(crate) struct Configuration<P> {
+    pub(crate) on_logout: LogoutBehaviour,
+    pub(crate) login_deadline: Option<Duration>,
+    pub(crate) visit_deadline: Option<Duration>,
+    pub(crate) claims_key: &'static str,
+    pub(crate) auth_level_key: &'static str,
+    pub(crate) post_logout_redirect_uri_key: &'static str,
+    pub(crate) allow_redirect_hosts: Vec<String>,
+    pub(crate) on_identity_expired: Option<OnIdentityExpired>,
+    pub(crate) policy: Arc<P>,
+}
+
+impl Default for Configuration<SessionIdentityPolicy> {
+    fn default() -> Self {
+        Self {
+            on_logout: LogoutBehaviour::PurgeSession,
+            login_deadline: None,
+            visit_deadline: None,
+            claims_key: "nervemq-claims",
+            auth_level_key: "nervemq-auth-level",
+            post_logout_redirect_uri_key: "post-logout-redirect-uri",
+            allow_redirect_hosts: Vec::new(),
+            on_identity_expired: None,
+            policy: Arc::new(SessionIdentityPolicy::default()),
+        }
+    }
+}
+
+/// A fluent builder to construct an [`IdentityMiddleware`] instance with custom configuration
+/// parameters.
+///
+/// The builder is generic over the [`IdentityPolicy`] used to persist the core identity; it
+/// defaults to [`SessionIdentityPolicy`]. Swap in a different backend with [`policy`].
+///
+/// Use [`IdentityMiddleware::builder`] to get started.
+///
+/// [`policy`]: Self::policy
+#[derive(Clone)]
+pub struct IdentityMiddlewareBuilder<P = SessionIdentityPolicy> {
+    configuration: Configuration<P>,
+}
+
+impl IdentityMiddlewareBuilder<SessionIdentityPolicy> {
+    pub(crate) fn new() -> Self {
+        Self {
+            configuration: Configuration::default(),
+        }
+    }
+}
+
+impl<P: IdentityPolicy> IdentityMiddlewareBuilder<P> {
+    /// Determines how [`Identity::logout`] affects the current session.
+    ///
+    /// By default, the current session is purged ([`LogoutBehaviour::PurgeSession`]).
+    ///
+    /// [`Identity::logout`]: crate::Identity::logout
+    pub fn logout_behaviour(mut self, logout_behaviour: LogoutBehaviour) -> Self {
+        self.configuration.on_logout = logout_behaviour;
+        self
+    }
+
+    /// Automatically logs out users after a certain amount of time has passed since they logged in,
+    /// regardless of their activity pattern.
+    ///
+    /// If set to `None`, the login deadline is disabled. Disabled by default.
+    pub fn login_deadline(mut self, deadline: Option<Duration>) -> Self {
+        self.configuration.login_deadline = deadline;
+        self
+    }
+
+    /// Automatically logs out users after a certain amount of time has passed since their last
+    /// visit.
+    ///
+    /// If set to `None`, the visit deadline is disabled. Disabled by default.
+    pub fn visit_deadline(mut self, deadline: Option<Duration>) -> Self {
+        self.configuration.visit_deadline = deadline;
+        self
+    }
+
+    /// Overrides the session key under which the structured claims blob is stored.
+    ///
+    /// Mirrors the subject's `id_key`. Defaults to `"nervemq-claims"`. See
+    /// [`Identity::login_with_claims`].
+    ///
+    /// [`Identity::login_with_claims`]: crate::Identity::login_with_claims
+    pub fn claims_key(mut self, claims_key: &'static str) -> Self {
+        self.configuration.claims_key = claims_key;
+        self
+    }
+
+    /// Restricts the set of hosts that [`Identity::logout_with_redirect`] is allowed to redirect to.
+    ///
+    /// Relative redirect URIs (without an authority component) are always permitted. An absolute
+    /// URI is only honoured if its host appears in `hosts`; otherwise the middleware ignores the
+    /// redirect and falls back to the handler's response. By default no external host is allowed.
+    ///
+    /// [`Identity::logout_with_redirect`]: crate::Identity::logout_with_redirect
+    pub fn allow_redirect_hosts(mut self, hosts: impl IntoIterator<Item = String>) -> Self {
+        self.configuration.allow_redirect_hosts = hosts.into_iter().collect();
+        self
+    }
+
+    /// Swaps the [`IdentityPolicy`] that persists the core identity.
+    ///
+    /// Defaults to [`SessionIdentityPolicy`], which preserves the [`actix_session`]-backed
+    /// behaviour. Provide a custom implementation to back the identity with a signed cookie, a JWT
+    /// or an external store.
+    pub fn policy<P2: IdentityPolicy>(self, policy: P2) -> IdentityMiddlewareBuilder<P2> {
+        let Configuration {
+            on_logout,
+            login_deadline,
+            visit_deadline,
+            claims_key,
+            auth_level_key,
+            post_logout_redirect_uri_key,
+            allow_redirect_hosts,
+            on_identity_expired,
+            policy: _,
+        } = self.configuration;
+
+        IdentityMiddlewareBuilder {
+            configuration: Configuration {
+                on_logout,
+                login_deadline,
+                visit_deadline,
+                claims_key,
+                auth_level_key,
+                post_logout_redirect_uri_key,
+                allow_redirect_hosts,
+                on_identity_expired,
+                policy: Arc::new(policy),
+            },
+        }
+    }
+
+    /// Registers a callback invoked when an identity is dropped because a deadline elapsed.
+    ///
+    /// The callback receives the expired user id and the [`ExpiryReason`], and runs right before
+    /// the identity is forgotten according to the configured [`LogoutBehaviour`]. This is the only point at which an application can observe a deadline
+    /// lapse, since the identity is gone by the time the next request arrives — use it to emit an
+    /// audit log, bump a metric, or invalidate a server-side cache.
+    ///
+    /// [`ExpiryReason`]: crate::ExpiryReason
+    pub fn on_identity_expired(
+        mut self,
+        callback: impl Fn(&str, crate::expiry::ExpiryReason) + Send + Sync + 'static,
+    ) -> Self {
+        self.configuration.on_identity_expired = Some(Arc::new(callback));
+        self
+    }
+
+    /// Finalises the builder and returns an [`IdentityMiddleware`] instance.
+    pub fn build(self) -> IdentityMiddleware<P> {
+        IdentityMiddleware::new(self.configuration)
+    }
+}
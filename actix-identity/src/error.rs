@@ -0,0 +1,124 @@
+//! Failure modes of identity operations.
+
+use actix_session::{SessionGetError, SessionInsertError};
+use actix_web::{cookie::time::error::ComponentRange, http::StatusCode, ResponseError};
+use derive_more::{Display, Error, From};
+
+/// Error that can occur during login attempts, or while persisting a feature extra (the claims
+/// blob, the auth level, the post-logout redirect marker) through the configured policy.
+#[derive(Debug, Display, Error, From)]
+#[non_exhaustive]
+pub enum LoginError {
+    /// Failed to write to the session store.
+    #[display("{_0}")]
+    SessionInsertError(SessionInsertError),
+
+    /// Failed to serialize a value for storage.
+    #[display("{_0}")]
+    SerializationError(serde_json::Error),
+}
+
+impl ResponseError for LoginError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+}
+
+/// Errors that can occur while retrieving an identity.
+#[derive(Debug, Display, Error, From)]
+#[non_exhaustive]
+pub enum GetIdentityError {
+    /// The session has expired.
+    #[display("The given session has expired and is no longer valid")]
+    SessionExpiryError(SessionExpiryError),
+
+    /// No identity is found in a session.
+    #[display("No identity found in session")]
+    MissingIdentityError(MissingIdentityError),
+
+    /// No structured claims blob is stored alongside the identity.
+    #[display("{_0}")]
+    MissingClaimsError(MissingClaimsError),
+
+    /// Failed to access the session store.
+    #[display("Failed to accessing the session store")]
+    SessionGetError(SessionGetError),
+
+    /// Failed to write to the session store.
+    #[display("Failed to write to the session store")]
+    SessionInsertError(SessionInsertError),
+
+    /// Identity info was lost after being validated.
+    ///
+    /// Seeing this error indicates a bug in actix-identity.
+    #[display("Identity info was lost after being validated")]
+    LostIdentityError(LostIdentityError),
+
+    /// The post-logout redirect URI could not be validated.
+    #[display("The post-logout redirect URI is not a valid URI: {_0}")]
+    InvalidRedirectUriError(InvalidRedirectUriError),
+
+    /// The identity does not meet the required authentication assurance level.
+    #[display("{_0}")]
+    InsufficientAuthLevelError(InsufficientAuthLevelError),
+
+    /// Failed to persist a feature extra (the post-logout redirect marker, the claims blob, or the
+    /// auth level) through the configured policy.
+    #[display("{_0}")]
+    LoginError(LoginError),
+}
+
+impl ResponseError for GetIdentityError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            // Authenticated, but not assured enough for the route: 403, not 401.
+            GetIdentityError::InsufficientAuthLevelError(_) => StatusCode::FORBIDDEN,
+            _ => StatusCode::UNAUTHORIZED,
+        }
+    }
+}
+
+/// The session has expired and the stored login timestamp could no longer be parsed.
+#[derive(Debug, Display, Error, From)]
+#[display("The given session has expired and is no longer valid")]
+pub struct SessionExpiryError(pub(crate) ComponentRange);
+
+/// There is no identity stored in the session.
+#[derive(Debug, Display, Error)]
+#[display("There is no identity stored in the session")]
+#[non_exhaustive]
+pub struct MissingIdentityError;
+
+/// The identity stored in the session was lost after having been validated.
+#[derive(Debug, Display, Error)]
+#[display("The identity stored in the session was lost after having been validated")]
+#[non_exhaustive]
+pub struct LostIdentityError;
+
+/// There is no structured claims blob stored alongside the identity, or the stored value does not
+/// match the requested type.
+#[derive(Debug, Display, Error)]
+#[display("There is no claims blob of the requested type stored alongside the identity")]
+#[non_exhaustive]
+pub struct MissingClaimsError;
+
+/// The post-logout redirect URI passed to [`Identity::logout_with_redirect`] was not a valid URI.
+///
+/// [`Identity::logout_with_redirect`]: crate::Identity::logout_with_redirect
+#[derive(Debug, Display, Error)]
+#[display("`{_0}` is not a valid post-logout redirect URI")]
+pub struct InvalidRedirectUriError(pub String);
+
+/// The current identity's authentication assurance level is below the level required by the route.
+///
+/// [`RequireAuthLevel`]: crate::RequireAuthLevel
+#[derive(Debug, Display, Error)]
+#[display("authentication assurance level {actual} is below the required level {required}")]
+#[non_exhaustive]
+pub struct InsufficientAuthLevelError {
+    /// The assurance level required by the route.
+    pub required: u8,
+
+    /// The assurance level the current identity actually holds.
+    pub actual: u8,
+}
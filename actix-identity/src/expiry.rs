@@ -0,0 +1,34 @@
+//! Hooks for reacting when an identity is dropped due to an expired deadline.
+
+use std::sync::Arc;
+
+/// Why an identity was forgotten by [`IdentityMiddleware`].
+///
+/// The middleware drops an identity once either deadline elapses. Distinguishing the two lets
+/// applications treat a forced logout (the absolute login lifetime was reached) differently from an
+/// idle timeout (no activity within the visit deadline).
+///
+/// [`IdentityMiddleware`]: crate::IdentityMiddleware
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpiryReason {
+    /// The absolute login deadline elapsed: `logged_at` + the configured login deadline is in the
+    /// past, regardless of activity.
+    LoginDeadline,
+
+    /// The idle visit deadline elapsed: `last_visited_at` + the configured visit deadline is in the
+    /// past.
+    VisitDeadline,
+}
+
+/// Callback invoked with the expired user id and the [`ExpiryReason`], right before the middleware
+/// forgets the identity according to the configured [`LogoutBehaviour`].
+///
+/// [`LogoutBehaviour`]: crate::config::LogoutBehaviour
+///
+/// Configured through [`IdentityMiddlewareBuilder::on_identity_expired`]. Because expiry is handled
+/// internally and the id is gone by the time the next request arrives, this is the only place an
+/// application can observe the lapse — emit an audit log, increment a metric, or invalidate a
+/// server-side cache or refresh token.
+///
+/// [`IdentityMiddlewareBuilder::on_identity_expired`]: crate::config::IdentityMiddlewareBuilder::on_identity_expired
+pub type OnIdentityExpired = Arc<dyn Fn(&str, ExpiryReason) + Send + Sync>;
@@ -1,19 +1,23 @@
+use std::sync::Arc;
+
 use actix_session::Session;
 use actix_utils::future::{ready, Ready};
 use actix_web::{
     cookie::time::OffsetDateTime,
     dev::{Extensions, Payload},
     http::StatusCode,
-    Error, FromRequest, HttpMessage, HttpRequest, HttpResponse,
+    Error, FromRequest, HttpMessage, HttpRequest, HttpResponse, ResponseError,
 };
+use serde::{de::DeserializeOwned, Serialize};
 use serde_json::Value;
 
 use crate::{
     config::LogoutBehaviour,
     error::{
-        GetIdentityError, InvalidIdTypeError, LoginError, LostIdentityError, MissingIdentityError,
-        SessionExpiryError,
+        GetIdentityError, InsufficientAuthLevelError, InvalidRedirectUriError, LoginError,
+        LostIdentityError, MissingClaimsError, MissingIdentityError,
     },
+    policy::{forget_identity, IdentityPolicy, SessionIdentityPolicy, StoredIdentity},
 };
 
 /// A verified user identity. It can be used as a request extractor.
@@ -25,7 +29,7 @@ use crate::{
 /// # Examples
 /// ```
 /// use actix_web::{
-///     get, post, Responder, HttpRequest, HttpMessage, HttpResponse
+///     get, post, Responder, HttpRequest, HttpResponse
 /// };
 /// use actix_identity::Identity;
 ///
@@ -40,7 +44,7 @@ use crate::{
 ///
 /// #[post("/login")]
 /// async fn login(request: HttpRequest) -> impl Responder {
-///     Identity::login(&request.extensions(), "User1".into());
+///     Identity::login(&request, "User1".into());
 ///     HttpResponse::Ok()
 /// }
 ///
@@ -78,22 +82,28 @@ use crate::{
 /// ```
 pub struct Identity(IdentityInner);
 
+/// The pieces of an [`IdentityMiddleware`]'s configuration that a handler needs after extraction.
+///
+/// Attached to the request [`Extensions`] by the middleware for every request; carries no
+/// back-reference to the request itself, so it is safe to store there.
+///
+/// [`IdentityMiddleware`]: crate::IdentityMiddleware
 #[derive(Clone)]
-pub(crate) struct IdentityInner {
-    pub(crate) session: Session,
+pub(crate) struct IdentityConfig {
+    pub(crate) policy: Arc<dyn IdentityPolicy>,
     pub(crate) logout_behaviour: LogoutBehaviour,
     pub(crate) is_login_deadline_enabled: bool,
     pub(crate) is_visit_deadline_enabled: bool,
-    pub(crate) id_key: &'static str,
-    pub(crate) last_visit_unix_timestamp_key: &'static str,
-    pub(crate) login_unix_timestamp_key: &'static str,
+    pub(crate) post_logout_redirect_uri_key: &'static str,
+    pub(crate) claims_key: &'static str,
+    pub(crate) auth_level_key: &'static str,
 }
 
-impl IdentityInner {
+impl IdentityConfig {
     fn extract(ext: &Extensions) -> Self {
         ext.get::<Self>()
             .expect(
-                "No `IdentityInner` instance was found in the extensions attached to the \
+                "No `IdentityConfig` instance was found in the extensions attached to the \
                 incoming request. This usually means that `IdentityMiddleware` has not been \
                 registered as an application middleware via `App::wrap`. `Identity` cannot be used \
                 unless the identity machine is properly mounted: register `IdentityMiddleware` as \
@@ -102,20 +112,37 @@ impl IdentityInner {
             )
             .to_owned()
     }
+}
+
+#[derive(Clone)]
+pub(crate) struct IdentityInner {
+    pub(crate) config: IdentityConfig,
+
+    /// The identity as last loaded through the configured [`IdentityPolicy`].
+    pub(crate) stored: StoredIdentity,
+
+    /// The session attached to the request, if `actix_session::SessionMiddleware` is mounted.
+    ///
+    /// The core identity never touches this directly (see [`IdentityPolicy`]); it exists only to
+    /// back the claims/auth-level/post-logout-redirect feature extras, which are session-backed
+    /// regardless of which policy persists the core identity.
+    pub(crate) session: Option<Session>,
 
-    /// Retrieve the user id attached to the current session.
-    fn get_identity(&self) -> Result<String, GetIdentityError> {
-        self.session
-            .get_value(self.id_key)
-            .ok_or_else(|| MissingIdentityError.into())
-            .and_then(|value| match value {
-                Value::String(s) => Ok(s),
-                Value::Null => Err(InvalidIdTypeError("null").into()),
-                Value::Bool(_) => Err(InvalidIdTypeError("bool").into()),
-                Value::Number(_) => Err(InvalidIdTypeError("number").into()),
-                Value::Array(_) => Err(InvalidIdTypeError("array").into()),
-                Value::Object(_) => Err(InvalidIdTypeError("object").into()),
-            })
+    /// The request this identity is attached to, cached so that [`Identity::logout`] and similar
+    /// methods can reach the policy again through `Extensions` without re-inserting themselves into
+    /// those same extensions (which would leak a reference cycle). `None` only for [`Identity::mock`],
+    /// which has no real request to cache.
+    pub(crate) request: Option<HttpRequest>,
+}
+
+impl IdentityInner {
+    /// Re-read the core identity through the configured policy when a live request is cached,
+    /// falling back to the snapshot captured at construction time otherwise.
+    fn reload(&self) -> Option<StoredIdentity> {
+        match &self.request {
+            Some(request) => self.config.policy.load(&request.extensions()),
+            None => Some(self.stored.clone()),
+        }
     }
 }
 
@@ -123,17 +150,29 @@ impl Identity {
     /// Useful for testing
     pub fn mock(id: String) -> Self {
         let session = Session::mock(Default::default(), actix_session::SessionStatus::Unchanged);
+        let policy: Arc<dyn IdentityPolicy> = Arc::new(SessionIdentityPolicy::default());
+        let stored = StoredIdentity {
+            id,
+            ..Default::default()
+        };
 
-        session.insert("nervemq-id", id).unwrap();
+        let mut ext = Extensions::new();
+        ext.insert(session.clone());
+        policy.store(&ext, &stored).unwrap();
 
         Self(IdentityInner {
-            session,
-            logout_behaviour: LogoutBehaviour::PurgeSession,
-            is_login_deadline_enabled: false,
-            is_visit_deadline_enabled: false,
-            id_key: "nervemq-id",
-            last_visit_unix_timestamp_key: "last-visit-timestamp",
-            login_unix_timestamp_key: "login-timestamp",
+            config: IdentityConfig {
+                policy,
+                logout_behaviour: LogoutBehaviour::PurgeSession,
+                is_login_deadline_enabled: false,
+                is_visit_deadline_enabled: false,
+                post_logout_redirect_uri_key: "post-logout-redirect-uri",
+                claims_key: "nervemq-claims",
+                auth_level_key: "nervemq-auth-level",
+            },
+            stored,
+            session: Some(session),
+            request: None,
         })
     }
 
@@ -155,8 +194,8 @@ impl Identity {
     /// ```
     pub fn id(&self) -> Result<String, GetIdentityError> {
         self.0
-            .session
-            .get(self.0.id_key)?
+            .reload()
+            .map(|stored| stored.id)
             .ok_or_else(|| LostIdentityError.into())
     }
 
@@ -168,29 +207,196 @@ impl Identity {
     ///
     /// # Examples
     /// ```
-    /// use actix_web::{post, Responder, HttpRequest, HttpMessage, HttpResponse};
+    /// use actix_web::{post, Responder, HttpRequest, HttpResponse};
+    /// use actix_identity::Identity;
+    ///
+    /// #[post("/login")]
+    /// async fn login(request: HttpRequest) -> impl Responder {
+    ///     Identity::login(&request, "User1".into());
+    ///     HttpResponse::Ok()
+    /// }
+    /// ```
+    pub fn login(req: &HttpRequest, id: String) -> Result<Self, LoginError> {
+        let ext = req.extensions();
+        let config = IdentityConfig::extract(&ext);
+        let now = OffsetDateTime::now_utc();
+        let stored = StoredIdentity {
+            id,
+            logged_at: config.is_login_deadline_enabled.then_some(now),
+            last_visited_at: config.is_visit_deadline_enabled.then_some(now),
+        };
+        config.policy.store(&ext, &stored)?;
+        config.policy.renew(&ext);
+
+        let session = ext.get::<Session>().cloned();
+        Ok(Self(IdentityInner {
+            config,
+            stored,
+            session,
+            request: Some(req.clone()),
+        }))
+    }
+
+    /// Attach a valid user identity to the current session, together with a structured claims blob.
+    ///
+    /// Behaves exactly like [`login`], but additionally serializes `claims` into a dedicated session
+    /// key (separate from the subject stored under `id_key`). This lets OIDC-style applications
+    /// persist roles, email, tenant, issued-at and similar attributes alongside the subject, and
+    /// retrieve them later via [`claims`] without a second round-trip to the session store.
+    ///
+    /// [`id`] keeps returning the subject string, so code that only needs the user id is unaffected.
+    ///
+    /// # Examples
+    /// ```
+    /// use actix_web::{post, Responder, HttpRequest, HttpResponse};
     /// use actix_identity::Identity;
+    /// use serde::{Deserialize, Serialize};
+    ///
+    /// #[derive(Serialize, Deserialize)]
+    /// struct Claims {
+    ///     email: String,
+    ///     roles: Vec<String>,
+    /// }
     ///
     /// #[post("/login")]
     /// async fn login(request: HttpRequest) -> impl Responder {
-    ///     Identity::login(&request.extensions(), "User1".into());
+    ///     let claims = Claims { email: "user1@example.com".into(), roles: vec!["admin".into()] };
+    ///     Identity::login_with_claims(&request, "User1".into(), &claims);
     ///     HttpResponse::Ok()
     /// }
     /// ```
-    pub fn login(ext: &Extensions, id: String) -> Result<Self, LoginError> {
-        let inner = IdentityInner::extract(ext);
-        inner.session.insert(inner.id_key, id)?;
-        let now = OffsetDateTime::now_utc().unix_timestamp();
-        if inner.is_login_deadline_enabled {
-            inner.session.insert(inner.login_unix_timestamp_key, now)?;
+    ///
+    /// [`login`]: Self::login
+    /// [`claims`]: Self::claims
+    /// [`id`]: Self::id
+    pub fn login_with_claims<T: Serialize>(
+        req: &HttpRequest,
+        id: String,
+        claims: &T,
+    ) -> Result<Self, LoginError> {
+        let identity = Self::login(req, id)?;
+        if let Some(session) = &identity.0.session {
+            let value = serde_json::to_value(claims)?;
+            identity
+                .0
+                .config
+                .policy
+                .store_extra(session, identity.0.config.claims_key, value)?;
+        }
+        Ok(identity)
+    }
+
+    /// Deserialize the structured claims blob attached to the current session.
+    ///
+    /// Returns the claims previously stored with [`login_with_claims`]. The subject remains
+    /// available through [`id`] regardless of whether claims were stored.
+    ///
+    /// # Examples
+    /// ```
+    /// use actix_web::{get, Responder};
+    /// use actix_identity::Identity;
+    /// use serde::{Deserialize, Serialize};
+    ///
+    /// #[derive(Serialize, Deserialize)]
+    /// struct Claims {
+    ///     email: String,
+    ///     roles: Vec<String>,
+    /// }
+    ///
+    /// #[get("/")]
+    /// async fn index(user: Identity) -> impl Responder {
+    ///     let claims = user.claims::<Claims>().unwrap();
+    ///     format!("Welcome! {}", claims.email)
+    /// }
+    /// ```
+    ///
+    /// [`login_with_claims`]: Self::login_with_claims
+    /// [`id`]: Self::id
+    pub fn claims<T: DeserializeOwned>(&self) -> Result<T, GetIdentityError> {
+        let session = self.0.session.as_ref().ok_or(MissingClaimsError)?;
+        let value = self
+            .0
+            .config
+            .policy
+            .load_extra(session, self.0.config.claims_key)
+            .ok_or(MissingClaimsError)?;
+        serde_json::from_value(value).map_err(|_| MissingClaimsError.into())
+    }
+
+    /// Attach a valid user identity together with an authentication assurance level.
+    ///
+    /// Behaves like [`login`], but also records a numeric assurance level that routes can later
+    /// require via [`RequireAuthLevel`]. Higher levels denote stronger proof (e.g. `0` for a plain
+    /// password login, `1` after a second factor). The level can be raised after the fact with
+    /// [`step_up`].
+    ///
+    /// [`login`]: Self::login
+    /// [`step_up`]: Self::step_up
+    pub fn login_with_level(req: &HttpRequest, id: String, level: u8) -> Result<Self, LoginError> {
+        let identity = Self::login(req, id)?;
+        if let Some(session) = &identity.0.session {
+            let value = serde_json::to_value(level)?;
+            identity
+                .0
+                .config
+                .policy
+                .store_extra(session, identity.0.config.auth_level_key, value)?;
         }
-        if inner.is_visit_deadline_enabled {
-            inner
-                .session
-                .insert(inner.last_visit_unix_timestamp_key, now)?;
+        Ok(identity)
+    }
+
+    /// Return the authentication assurance level recorded for the current identity.
+    ///
+    /// Identities established through [`login`] (rather than [`login_with_level`]) default to level
+    /// `0`.
+    ///
+    /// [`login`]: Self::login
+    /// [`login_with_level`]: Self::login_with_level
+    pub fn auth_level(&self) -> Result<u8, GetIdentityError> {
+        let Some(session) = &self.0.session else {
+            return Ok(0);
+        };
+        Ok(self
+            .0
+            .config
+            .policy
+            .load_extra(session, self.0.config.auth_level_key)
+            .and_then(|value| serde_json::from_value::<u8>(value).ok())
+            .unwrap_or(0))
+    }
+
+    /// Raise the authentication assurance level after a second factor has succeeded.
+    ///
+    /// The level is only ever increased; calling this with a value below the current level leaves
+    /// the recorded level untouched.
+    pub fn step_up(&self, level: u8) -> Result<(), LoginError> {
+        let current = self.auth_level().unwrap_or(0);
+        if level > current {
+            if let Some(session) = &self.0.session {
+                let value = serde_json::to_value(level)?;
+                self.0
+                    .config
+                    .policy
+                    .store_extra(session, self.0.config.auth_level_key, value)?;
+            }
         }
-        inner.session.renew();
-        Ok(Self(inner))
+        Ok(())
+    }
+
+    /// Return the instant at which the current identity was (re-)authenticated.
+    ///
+    /// This reuses the login-timestamp machinery, so middleware guarding sensitive routes can force
+    /// a re-authentication once a configurable interval has elapsed since this instant.
+    ///
+    /// # Preconditions
+    /// The login timestamp is only recorded when the login deadline is enabled via
+    /// [`IdentityMiddlewareBuilder::login_deadline`]. With the login deadline disabled this method
+    /// always returns `Ok(None)`, so interval-based forced re-authentication requires the login
+    /// deadline to be configured.
+    ///
+    /// [`IdentityMiddlewareBuilder::login_deadline`]: crate::config::IdentityMiddlewareBuilder::login_deadline
+    pub fn reauthenticated_at(&self) -> Result<Option<OffsetDateTime>, GetIdentityError> {
+        self.logged_at()
     }
 
     /// Remove the user identity from the current session.
@@ -200,6 +406,11 @@ impl Identity {
     ///
     /// The behaviour on logout is determined by [`IdentityMiddlewareBuilder::logout_behaviour`].
     ///
+    /// Identities obtained from an extractor or from [`login`]/[`login_with_claims`]/
+    /// [`login_with_level`] always clear the core identity through the configured policy.
+    /// [`Identity::mock`] is the only exception: it has no real request to forget through, so calling
+    /// `logout` on it only clears the claims/auth-level session keys.
+    ///
     /// # Examples
     /// ```
     /// use actix_web::{post, Responder, HttpResponse};
@@ -213,54 +424,96 @@ impl Identity {
     /// ```
     ///
     /// [`IdentityMiddlewareBuilder::logout_behaviour`]: crate::config::IdentityMiddlewareBuilder::logout_behaviour
+    /// [`login`]: Self::login
+    /// [`login_with_claims`]: Self::login_with_claims
+    /// [`login_with_level`]: Self::login_with_level
     pub fn logout(self) {
-        match self.0.logout_behaviour {
-            LogoutBehaviour::PurgeSession => {
-                self.0.session.purge();
-            }
-            LogoutBehaviour::DeleteIdentityKeys => {
-                self.0.session.remove(self.0.id_key);
-                if self.0.is_login_deadline_enabled {
-                    self.0.session.remove(self.0.login_unix_timestamp_key);
-                }
-                if self.0.is_visit_deadline_enabled {
-                    self.0.session.remove(self.0.last_visit_unix_timestamp_key);
-                }
+        let Some(request) = &self.0.request else {
+            // `Identity::mock` has no real request to forget the core identity through; fall back
+            // to clearing the session-backed extras directly.
+            if let Some(session) = &self.0.session {
+                session.remove(self.0.config.claims_key);
+                session.remove(self.0.config.auth_level_key);
             }
+            return;
+        };
+
+        forget_identity(
+            self.0.config.policy.as_ref(),
+            &request.extensions(),
+            self.0.config.logout_behaviour.clone(),
+            self.0.config.claims_key,
+            self.0.config.auth_level_key,
+        );
+    }
+
+    /// Declare the intent to log out and redirect the user agent afterwards.
+    ///
+    /// Unlike [`logout`], this method does not clear the identity inline. It records the
+    /// post-logout redirect URI and defers the actual clearing to [`IdentityMiddleware`], modelled
+    /// on OpenID Connect RP-initiated logout: the middleware inspects the recorded intent on the
+    /// response path, applies the configured [`LogoutBehaviour`] through the policy, and rewrites
+    /// the outgoing response into a `303 See Other` pointing at `post_logout_uri` (falling back to
+    /// the handler's response when no URI is recorded or its host is not allowed).
+    ///
+    /// Decoupling "declare intent" from "clear state" keeps logout composable with other
+    /// middleware and lets a single `async fn logout(id: Identity)` both clear the session and
+    /// bounce the user to an external IdP end-session endpoint.
+    ///
+    /// # Errors
+    /// Returns [`InvalidRedirectUriError`] if `post_logout_uri` is not a valid URI. The middleware
+    /// enforces the host allow/deny policy configured via
+    /// [`IdentityMiddlewareBuilder::allow_redirect_hosts`].
+    ///
+    /// [`logout`]: Self::logout
+    /// [`IdentityMiddleware`]: crate::IdentityMiddleware
+    /// [`IdentityMiddlewareBuilder::allow_redirect_hosts`]: crate::config::IdentityMiddlewareBuilder::allow_redirect_hosts
+    pub fn logout_with_redirect(self, post_logout_uri: String) -> Result<(), GetIdentityError> {
+        if post_logout_uri.parse::<actix_web::http::Uri>().is_err() {
+            return Err(InvalidRedirectUriError(post_logout_uri).into());
+        }
+        if let Some(session) = &self.0.session {
+            self.0.config.policy.store_extra(
+                session,
+                self.0.config.post_logout_redirect_uri_key,
+                Value::String(post_logout_uri),
+            )?;
         }
+        Ok(())
     }
 
-    pub(crate) fn extract(ext: &Extensions) -> Result<Self, GetIdentityError> {
-        let inner = IdentityInner::extract(ext);
-        inner.get_identity()?;
-        Ok(Self(inner))
+    pub(crate) fn extract(req: &HttpRequest) -> Result<Self, GetIdentityError> {
+        let config = IdentityConfig::extract(&req.extensions());
+        let stored = config
+            .policy
+            .load(&req.extensions())
+            .ok_or_else(|| MissingIdentityError.into())?;
+        let session = req.extensions().get::<Session>().cloned();
+
+        Ok(Self(IdentityInner {
+            config,
+            stored,
+            session,
+            request: Some(req.clone()),
+        }))
     }
 
     pub(crate) fn logged_at(&self) -> Result<Option<OffsetDateTime>, GetIdentityError> {
-        Ok(self
-            .0
-            .session
-            .get(self.0.login_unix_timestamp_key)?
-            .map(OffsetDateTime::from_unix_timestamp)
-            .transpose()
-            .map_err(SessionExpiryError)?)
+        Ok(self.0.reload().and_then(|s| s.logged_at))
     }
 
     pub(crate) fn last_visited_at(&self) -> Result<Option<OffsetDateTime>, GetIdentityError> {
-        Ok(self
-            .0
-            .session
-            .get(self.0.last_visit_unix_timestamp_key)?
-            .map(OffsetDateTime::from_unix_timestamp)
-            .transpose()
-            .map_err(SessionExpiryError)?)
+        Ok(self.0.reload().and_then(|s| s.last_visited_at))
     }
 
     pub(crate) fn set_last_visited_at(&self) -> Result<(), LoginError> {
-        let now = OffsetDateTime::now_utc().unix_timestamp();
-        self.0
-            .session
-            .insert(self.0.last_visit_unix_timestamp_key, now)?;
+        if let Some(request) = &self.0.request {
+            let ext = request.extensions();
+            if let Some(mut stored) = self.0.config.policy.load(&ext) {
+                stored.last_visited_at = Some(OffsetDateTime::now_utc());
+                self.0.config.policy.store(&ext, &stored)?;
+            }
+        }
         Ok(())
     }
 }
@@ -287,7 +540,7 @@ impl FromRequest for Identity {
 
     #[inline]
     fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
-        ready(Identity::extract(&req.extensions()).map_err(|err| {
+        ready(Identity::extract(req).map_err(|err| {
             let res = actix_web::error::InternalError::from_response(
                 err,
                 HttpResponse::new(StatusCode::UNAUTHORIZED),
@@ -297,3 +550,66 @@ impl FromRequest for Identity {
         }))
     }
 }
+
+/// Extractor that requires the current identity to have at least assurance level `N`.
+///
+/// Wraps [`Identity`] for routes that demand a stronger proof than a plain password login — MFA or
+/// WebAuthn step-up flows. Extraction succeeds only if the identity is valid *and* its
+/// [`auth_level`] is at least `N`; otherwise a [`InsufficientAuthLevelError`] is returned, mapped to
+/// `403 FORBIDDEN` (an authenticated-but-underprivileged caller), while a missing identity keeps the
+/// usual `401 UNAUTHORIZED`.
+///
+/// # Examples
+/// ```
+/// use actix_web::{get, Responder};
+/// use actix_identity::RequireAuthLevel;
+///
+/// #[get("/transfer")]
+/// async fn transfer(user: RequireAuthLevel<2>) -> impl Responder {
+///     format!("high-assurance action for {}", user.id().unwrap())
+/// }
+/// ```
+///
+/// [`auth_level`]: Identity::auth_level
+pub struct RequireAuthLevel<const N: u8>(pub Identity);
+
+impl<const N: u8> std::ops::Deref for RequireAuthLevel<N> {
+    type Target = Identity;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<const N: u8> FromRequest for RequireAuthLevel<N> {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    #[inline]
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let result = Identity::extract(req).and_then(|identity| {
+            let level = identity.auth_level()?;
+            if level >= N {
+                Ok(Self(identity))
+            } else {
+                Err(InsufficientAuthLevelError {
+                    required: N,
+                    actual: level,
+                }
+                .into())
+            }
+        });
+
+        ready(result.map_err(|err| {
+            // `GetIdentityError::status_code()` already maps `InsufficientAuthLevelError` to 403
+            // and everything else to 401.
+            let status = err.status_code();
+            let res = actix_web::error::InternalError::from_response(
+                err,
+                HttpResponse::new(status),
+            );
+
+            actix_web::Error::from(res)
+        }))
+    }
+}
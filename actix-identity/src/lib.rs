@@ -0,0 +1,27 @@
+//! Identity management for Actix Web.
+//!
+//! `actix-identity` can be used to track the identity of a user across multiple requests. It is
+//! built on top of [`actix-session`], which provides the underlying session storage.
+//!
+//! # Workflow
+//! 1. Register [`IdentityMiddleware`] and `actix_session`'s `SessionMiddleware` as middleware on
+//!    your `App`;
+//! 2. Call [`Identity::login`] to attach an identity to a session after a user authenticates;
+//! 3. Extract an [`Identity`] in your handlers to access the logged-in user;
+//! 4. Call [`Identity::logout`] (or [`Identity::logout_with_redirect`]) to drop the identity.
+//!
+//! [`actix-session`]: actix_session
+
+pub mod config;
+pub mod error;
+pub mod expiry;
+mod identity;
+mod middleware;
+pub mod policy;
+
+pub use self::{
+    expiry::{ExpiryReason, OnIdentityExpired},
+    identity::{Identity, RequireAuthLevel},
+    middleware::IdentityMiddleware,
+    policy::{IdentityPolicy, SessionIdentityPolicy, StoredIdentity},
+};
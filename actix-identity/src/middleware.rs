@@ -0,0 +1,276 @@
+use std::{rc::Rc, sync::Arc};
+
+use actix_session::Session;
+use actix_utils::future::{ready, Ready};
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    cookie::time::OffsetDateTime,
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    http::{header::LOCATION, StatusCode},
+    Error, HttpMessage, HttpResponse,
+};
+use futures_core::future::LocalBoxFuture;
+
+use crate::{
+    config::{Configuration, IdentityMiddlewareBuilder},
+    expiry::ExpiryReason,
+    identity::IdentityConfig,
+    policy::{forget_identity, IdentityPolicy, SessionIdentityPolicy},
+};
+
+/// Identity management middleware.
+///
+/// Mounts the identity machine so that [`Identity`] can be extracted from requests, enforces the
+/// configured login and visit deadlines, and applies the deferred logout recorded by
+/// [`Identity::logout_with_redirect`] on the response path.
+///
+/// The middleware is generic over the [`IdentityPolicy`] used to persist the core identity; it
+/// defaults to [`SessionIdentityPolicy`]. Configure a different policy through
+/// [`IdentityMiddlewareBuilder::policy`]. A policy is only ever handed the request's `Extensions`,
+/// so a fully custom, non-session-backed policy does not require `actix_session::SessionMiddleware`
+/// to be mounted — only the claims/auth-level/post-logout-redirect feature extras do.
+///
+/// [`Identity`]: crate::Identity
+/// [`Identity::logout_with_redirect`]: crate::Identity::logout_with_redirect
+/// [`IdentityMiddlewareBuilder::policy`]: crate::config::IdentityMiddlewareBuilder::policy
+pub struct IdentityMiddleware<P = SessionIdentityPolicy> {
+    configuration: Rc<Configuration<P>>,
+}
+
+impl<P> Clone for IdentityMiddleware<P> {
+    fn clone(&self) -> Self {
+        Self {
+            configuration: Rc::clone(&self.configuration),
+        }
+    }
+}
+
+impl<P> IdentityMiddleware<P> {
+    pub(crate) fn new(configuration: Configuration<P>) -> Self {
+        Self {
+            configuration: Rc::new(configuration),
+        }
+    }
+}
+
+impl IdentityMiddleware<SessionIdentityPolicy> {
+    /// A fluent API to configure [`IdentityMiddleware`].
+    pub fn builder() -> IdentityMiddlewareBuilder {
+        IdentityMiddlewareBuilder::new()
+    }
+}
+
+impl Default for IdentityMiddleware<SessionIdentityPolicy> {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+impl<S, B, P> Transform<S, ServiceRequest> for IdentityMiddleware<P>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+    P: IdentityPolicy,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = InnerIdentityMiddleware<S, P>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(InnerIdentityMiddleware {
+            service: Rc::new(service),
+            configuration: Rc::clone(&self.configuration),
+        }))
+    }
+}
+
+#[doc(hidden)]
+pub struct InnerIdentityMiddleware<S, P> {
+    service: Rc<S>,
+    configuration: Rc<Configuration<P>>,
+}
+
+impl<S, P> Clone for InnerIdentityMiddleware<S, P> {
+    fn clone(&self) -> Self {
+        Self {
+            service: Rc::clone(&self.service),
+            configuration: Rc::clone(&self.configuration),
+        }
+    }
+}
+
+impl<S, B, P> Service<ServiceRequest> for InnerIdentityMiddleware<S, P>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+    P: IdentityPolicy,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let srv = Rc::clone(&self.service);
+        let configuration = Rc::clone(&self.configuration);
+
+        Box::pin(async move {
+            // Enforce the configured deadlines before the request reaches the handler: an expired
+            // identity must not be observable by downstream extractors. The core identity is loaded
+            // and forgotten only through the configured policy, so a non-session-backed policy is
+            // enforced exactly like the default one.
+            enforce_deadlines(&configuration, &req);
+
+            let policy: Arc<dyn IdentityPolicy> = configuration.policy.clone();
+            let config = IdentityConfig {
+                policy,
+                logout_behaviour: configuration.on_logout.clone(),
+                is_login_deadline_enabled: configuration.login_deadline.is_some(),
+                is_visit_deadline_enabled: configuration.visit_deadline.is_some(),
+                post_logout_redirect_uri_key: configuration.post_logout_redirect_uri_key,
+                claims_key: configuration.claims_key,
+                auth_level_key: configuration.auth_level_key,
+            };
+            req.extensions_mut().insert(config);
+
+            let res = srv.call(req).await?;
+
+            // Apply any deferred RP-initiated logout recorded by the handler.
+            Ok(apply_pending_logout(&configuration, res))
+        })
+    }
+}
+
+/// Forget the identity, through the configured policy, if the login or visit deadline has elapsed.
+fn enforce_deadlines<P: IdentityPolicy>(configuration: &Configuration<P>, req: &ServiceRequest) {
+    let expired = {
+        let ext = req.extensions();
+        let Some(identity) = configuration.policy.load(&ext) else {
+            return;
+        };
+
+        let now = OffsetDateTime::now_utc();
+        let is_expired = |deadline: Option<std::time::Duration>, at: Option<OffsetDateTime>| {
+            deadline
+                .zip(at)
+                .is_some_and(|(deadline, at)| at + deadline < now)
+        };
+
+        let login_expired = is_expired(configuration.login_deadline, identity.logged_at);
+        let visit_expired = is_expired(configuration.visit_deadline, identity.last_visited_at);
+
+        // The login deadline is the stronger condition (absolute lifetime reached), so it wins when
+        // both elapsed.
+        let reason = if login_expired {
+            Some(ExpiryReason::LoginDeadline)
+        } else if visit_expired {
+            Some(ExpiryReason::VisitDeadline)
+        } else {
+            None
+        };
+
+        reason.map(|reason| (identity.id, reason))
+    };
+
+    let Some((id, reason)) = expired else {
+        return;
+    };
+
+    // Surface the lapse before the id is gone: this is the only place an application can
+    // observe it.
+    if let Some(callback) = &configuration.on_identity_expired {
+        callback(&id, reason);
+    }
+
+    forget_identity(
+        &*configuration.policy,
+        &req.extensions(),
+        configuration.on_logout.clone(),
+        configuration.claims_key,
+        configuration.auth_level_key,
+    );
+}
+
+/// Inspect the deferred-logout marker on the response path and, if present, clear the identity and
+/// rewrite the response into a `303 See Other` when a permitted redirect URI is recorded.
+fn apply_pending_logout<B, P>(
+    configuration: &Configuration<P>,
+    res: ServiceResponse<B>,
+) -> ServiceResponse<EitherBody<B>>
+where
+    B: MessageBody + 'static,
+    P: IdentityPolicy,
+{
+    let redirect_uri = res
+        .request()
+        .extensions()
+        .get::<Session>()
+        .and_then(|session| {
+            configuration
+                .policy
+                .load_extra(session, configuration.post_logout_redirect_uri_key)
+        })
+        .and_then(|value| value.as_str().map(str::to_owned));
+
+    let Some(redirect_uri) = redirect_uri else {
+        return res.map_into_left_body();
+    };
+
+    // The handler declared the intent to log out: the middleware — not the handler — clears state,
+    // routing the core identity removal through the configured policy.
+    forget_identity(
+        &*configuration.policy,
+        &res.request().extensions(),
+        configuration.on_logout.clone(),
+        configuration.claims_key,
+        configuration.auth_level_key,
+    );
+    if let Some(session) = res.request().extensions().get::<Session>() {
+        configuration
+            .policy
+            .forget_extra(session, configuration.post_logout_redirect_uri_key);
+    }
+
+    if !is_redirect_allowed(configuration, &redirect_uri) {
+        // Host not permitted: fall back to the handler's response rather than leaking an
+        // open redirect.
+        return res.map_into_left_body();
+    }
+
+    let redirect = HttpResponse::build(StatusCode::SEE_OTHER)
+        .insert_header((LOCATION, redirect_uri))
+        .finish()
+        .map_into_right_body();
+
+    res.into_response(redirect)
+}
+
+/// Relative URIs are always allowed; absolute URIs are honoured only when their host is listed in
+/// [`IdentityMiddlewareBuilder::allow_redirect_hosts`].
+///
+/// A leading `//` or `/\` is rejected outright rather than trusted as relative: `Uri::parse` treats
+/// `//evil.com/path` as having no host (it's network-path reference, not an authority-bearing URI),
+/// but browsers resolve a `Location: //evil.com/path` response header against the current scheme,
+/// i.e. exactly like an absolute redirect to `evil.com` — an open-redirect bypass of the allowlist
+/// below if left unchecked.
+///
+/// [`IdentityMiddlewareBuilder::allow_redirect_hosts`]: crate::config::IdentityMiddlewareBuilder::allow_redirect_hosts
+fn is_redirect_allowed<P>(configuration: &Configuration<P>, redirect_uri: &str) -> bool {
+    if redirect_uri.starts_with("//") || redirect_uri.starts_with("/\\") {
+        return false;
+    }
+
+    match redirect_uri.parse::<actix_web::http::Uri>() {
+        Ok(uri) => match uri.host() {
+            None => true,
+            Some(host) => configuration
+                .allow_redirect_hosts
+                .iter()
+                .any(|allowed| allowed == host),
+        },
+        Err(_) => false,
+    }
+}
@@ -0,0 +1,219 @@
+//! Pluggable storage policies for user identities.
+//!
+//! Historically the identity machinery was hard-wired to [`actix_session::Session`]: the id and the
+//! login/last-visit timestamps were read and written directly against a server-side session store.
+//! The [`IdentityPolicy`] trait abstracts that coupling, following the older policy-based design
+//! (e.g. a `CookieIdentityPolicy` that could be swapped for other backends): an implementation owns
+//! where and how the core identity is persisted, and [`IdentityMiddleware`] is generic over it.
+//!
+//! This unlocks self-contained signed-cookie identity (no server session store), JWT-backed
+//! stateless identity, and custom external stores, without forcing users to also mount
+//! [`actix_session::SessionMiddleware`]. [`SessionIdentityPolicy`] is the default implementation and
+//! preserves the original [`actix_session`]-backed behaviour.
+//!
+//! The claims blob, the authentication assurance level and the post-logout redirect marker are
+//! feature extras layered on top of the core identity (see [`IdentityPolicy::store_extra`]); unlike
+//! the core identity, they are keyed off an [`actix_session::Session`] directly, so using them still
+//! requires `SessionMiddleware` to be mounted regardless of which policy persists the core identity.
+//!
+//! [`IdentityMiddleware`]: crate::IdentityMiddleware
+
+use actix_session::Session;
+use actix_web::{cookie::time::OffsetDateTime, dev::Extensions};
+use serde_json::Value;
+
+use crate::{config::LogoutBehaviour, error::LoginError};
+
+/// The core persisted identity handled by an [`IdentityPolicy`].
+///
+/// Carries the subject id and the login/last-visit timestamps the middleware uses to enforce the
+/// login and visit deadlines. Feature extras such as the structured claims blob and the
+/// authentication assurance level are layered on top by [`Identity`] and are not part of this
+/// contract.
+///
+/// [`Identity`]: crate::Identity
+#[derive(Debug, Clone, Default)]
+pub struct StoredIdentity {
+    /// The subject id.
+    pub id: String,
+
+    /// When the identity was established, used to enforce the absolute login deadline.
+    pub logged_at: Option<OffsetDateTime>,
+
+    /// When the identity was last seen, used to enforce the idle visit deadline.
+    pub last_visited_at: Option<OffsetDateTime>,
+}
+
+/// Strategy describing where and how the core user identity is persisted.
+///
+/// Implementors decide the backing store; the middleware only drives the lifecycle, handing the
+/// request's [`Extensions`] to [`load`]/[`store`]/[`renew`]/[`forget`] rather than a concrete
+/// [`actix_session::Session`]. See [`SessionIdentityPolicy`] for the default
+/// [`actix_session`]-backed implementation.
+///
+/// [`load`]: Self::load
+/// [`store`]: Self::store
+/// [`renew`]: Self::renew
+/// [`forget`]: Self::forget
+pub trait IdentityPolicy: 'static {
+    /// Load the identity attached to the current request, if any.
+    fn load(&self, ext: &Extensions) -> Option<StoredIdentity>;
+
+    /// Persist `identity`, establishing or refreshing the stored timestamps.
+    fn store(&self, ext: &Extensions, identity: &StoredIdentity) -> Result<(), LoginError>;
+
+    /// Rotate the underlying storage token, preserving the stored identity.
+    fn renew(&self, ext: &Extensions);
+
+    /// Forget the identity according to `behaviour`.
+    fn forget(&self, ext: &Extensions, behaviour: LogoutBehaviour);
+
+    /// Persist an auxiliary value - the structured claims blob, the authentication assurance level,
+    /// or the post-logout redirect marker - under `key`, alongside the core identity.
+    ///
+    /// These extras are keyed off an explicit [`Session`] rather than [`Extensions`]: a policy that
+    /// has no use for them (e.g. a stateless, session-free backend) can simply leave the defaults,
+    /// which no-op.
+    fn store_extra(&self, _session: &Session, _key: &'static str, _value: Value) -> Result<(), LoginError> {
+        Ok(())
+    }
+
+    /// Load an auxiliary value previously stored via [`store_extra`](Self::store_extra).
+    fn load_extra(&self, _session: &Session, _key: &'static str) -> Option<Value> {
+        None
+    }
+
+    /// Remove an auxiliary value.
+    fn forget_extra(&self, _session: &Session, _key: &'static str) {}
+}
+
+/// Forget the core identity through `policy`, and, when `behaviour` is
+/// [`LogoutBehaviour::DeleteIdentityKeys`], also drop the claims and auth-level session extras
+/// alongside it.
+///
+/// All three logout paths (the deadline-enforcement middleware, the deferred
+/// `logout_with_redirect` middleware handling, and a plain [`Identity::logout`]) need to do exactly
+/// this, and drifted out of sync before: pulled out here so they can't drift again.
+///
+/// [`Identity::logout`]: crate::Identity::logout
+pub(crate) fn forget_identity(
+    policy: &dyn IdentityPolicy,
+    ext: &Extensions,
+    behaviour: LogoutBehaviour,
+    claims_key: &'static str,
+    auth_level_key: &'static str,
+) {
+    policy.forget(ext, behaviour.clone());
+    if matches!(behaviour, LogoutBehaviour::DeleteIdentityKeys) {
+        if let Some(session) = ext.get::<Session>() {
+            session.remove(claims_key);
+            session.remove(auth_level_key);
+        }
+    }
+}
+
+/// The default [`IdentityPolicy`], backed by [`actix_session::Session`].
+///
+/// Wraps the session logic that `IdentityInner` previously performed inline, keeping the previous
+/// key names so existing deployments keep reading and writing the same entries.
+#[derive(Debug, Clone)]
+pub struct SessionIdentityPolicy {
+    pub(crate) id_key: &'static str,
+    pub(crate) login_unix_timestamp_key: &'static str,
+    pub(crate) last_visit_unix_timestamp_key: &'static str,
+}
+
+impl Default for SessionIdentityPolicy {
+    fn default() -> Self {
+        Self {
+            id_key: "nervemq-id",
+            login_unix_timestamp_key: "login-timestamp",
+            last_visit_unix_timestamp_key: "last-visit-timestamp",
+        }
+    }
+}
+
+impl SessionIdentityPolicy {
+    /// Look up the [`Session`] attached to the request, if `actix_session::SessionMiddleware` put
+    /// one there. Unlike [`actix_session::SessionExt::get_session`], this never creates one: a
+    /// throwaway session that nothing will ever persist is worse than no session at all.
+    fn session(ext: &Extensions) -> Option<Session> {
+        ext.get::<Session>().cloned()
+    }
+
+    fn timestamp(session: &Session, key: &str) -> Option<OffsetDateTime> {
+        session
+            .get::<i64>(key)
+            .ok()
+            .flatten()
+            .and_then(|ts| OffsetDateTime::from_unix_timestamp(ts).ok())
+    }
+}
+
+impl IdentityPolicy for SessionIdentityPolicy {
+    fn load(&self, ext: &Extensions) -> Option<StoredIdentity> {
+        let session = Self::session(ext)?;
+        let id = match session.get_value(self.id_key)? {
+            serde_json::Value::String(s) => s,
+            _ => return None,
+        };
+        Some(StoredIdentity {
+            id,
+            logged_at: Self::timestamp(&session, self.login_unix_timestamp_key),
+            last_visited_at: Self::timestamp(&session, self.last_visit_unix_timestamp_key),
+        })
+    }
+
+    fn store(&self, ext: &Extensions, identity: &StoredIdentity) -> Result<(), LoginError> {
+        let Some(session) = Self::session(ext) else {
+            return Ok(());
+        };
+        session.insert(self.id_key, &identity.id)?;
+        match identity.logged_at {
+            Some(at) => session.insert(self.login_unix_timestamp_key, at.unix_timestamp())?,
+            None => {
+                session.remove(self.login_unix_timestamp_key);
+            }
+        }
+        match identity.last_visited_at {
+            Some(at) => session.insert(self.last_visit_unix_timestamp_key, at.unix_timestamp())?,
+            None => {
+                session.remove(self.last_visit_unix_timestamp_key);
+            }
+        }
+        Ok(())
+    }
+
+    fn renew(&self, ext: &Extensions) {
+        if let Some(session) = Self::session(ext) {
+            session.renew();
+        }
+    }
+
+    fn forget(&self, ext: &Extensions, behaviour: LogoutBehaviour) {
+        let Some(session) = Self::session(ext) else {
+            return;
+        };
+        match behaviour {
+            LogoutBehaviour::PurgeSession => session.purge(),
+            LogoutBehaviour::DeleteIdentityKeys => {
+                session.remove(self.id_key);
+                session.remove(self.login_unix_timestamp_key);
+                session.remove(self.last_visit_unix_timestamp_key);
+            }
+        }
+    }
+
+    fn store_extra(&self, session: &Session, key: &'static str, value: Value) -> Result<(), LoginError> {
+        session.insert(key, value)?;
+        Ok(())
+    }
+
+    fn load_extra(&self, session: &Session, key: &'static str) -> Option<Value> {
+        session.get_value(key)
+    }
+
+    fn forget_extra(&self, session: &Session, key: &'static str) {
+        session.remove(key);
+    }
+}
@@ -0,0 +1,481 @@
+//! Integration tests exercising the public identity API end-to-end through a full Actix Web app.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use actix_identity::{
+    policy::{IdentityPolicy, StoredIdentity},
+    ExpiryReason, Identity, IdentityMiddleware, RequireAuthLevel,
+};
+use actix_session::{storage::CookieSessionStore, SessionMiddleware};
+use actix_web::{
+    cookie::Key,
+    dev::Extensions,
+    get, post,
+    test::{self, TestRequest},
+    web, App, HttpRequest, HttpResponse, Responder,
+};
+use serde::{Deserialize, Serialize};
+
+pub(crate) fn session_middleware() -> SessionMiddleware<CookieSessionStore> {
+    SessionMiddleware::builder(CookieSessionStore::default(), Key::generate())
+        .cookie_secure(false)
+        .build()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Claims {
+    email: String,
+    roles: Vec<String>,
+}
+
+#[post("/login")]
+async fn login(req: HttpRequest) -> impl Responder {
+    Identity::login(&req, "user1".to_owned()).unwrap();
+    HttpResponse::Ok().finish()
+}
+
+#[get("/id")]
+async fn read_id(identity: Identity) -> impl Responder {
+    identity.id().unwrap()
+}
+
+/// An `IdentityPolicy` backed by a plain in-memory slot, entirely independent of
+/// `actix_session::Session`. It only ever touches the `Extensions` handed to it by
+/// `IdentityMiddleware`, proving that the policy abstraction doesn't secretly require
+/// `SessionMiddleware` to be mounted.
+#[derive(Clone, Default)]
+struct InMemoryIdentityPolicy(Arc<Mutex<Option<StoredIdentity>>>);
+
+impl IdentityPolicy for InMemoryIdentityPolicy {
+    fn load(&self, _ext: &Extensions) -> Option<StoredIdentity> {
+        self.0.lock().unwrap().clone()
+    }
+
+    fn store(
+        &self,
+        _ext: &Extensions,
+        identity: &StoredIdentity,
+    ) -> Result<(), actix_identity::error::LoginError> {
+        *self.0.lock().unwrap() = Some(identity.clone());
+        Ok(())
+    }
+
+    fn renew(&self, _ext: &Extensions) {}
+
+    fn forget(&self, _ext: &Extensions, _behaviour: actix_identity::config::LogoutBehaviour) {
+        *self.0.lock().unwrap() = None;
+    }
+}
+
+#[actix_web::test]
+async fn identity_round_trips_through_an_explicit_policy() {
+    // No `SessionMiddleware` is mounted here: the custom policy is entirely self-contained, which
+    // is the whole point of making `IdentityPolicy` generic over `Extensions`.
+    let app = test::init_service(
+        App::new()
+            .wrap(
+                IdentityMiddleware::builder()
+                    .policy(InMemoryIdentityPolicy::default())
+                    .build(),
+            )
+            .service(login)
+            .service(read_id),
+    )
+    .await;
+
+    let login = test::call_service(&app, TestRequest::post().uri("/login").to_request()).await;
+    assert!(login.status().is_success());
+
+    let req = TestRequest::get().uri("/id").to_request();
+    let id = test::call_and_read_body(&app, req).await;
+    assert_eq!(id, "user1");
+}
+
+#[post("/login-claims")]
+async fn login_claims(req: HttpRequest) -> impl Responder {
+    let claims = Claims {
+        email: "user1@example.com".to_owned(),
+        roles: vec!["admin".to_owned()],
+    };
+    Identity::login_with_claims(&req, "user1".to_owned(), &claims).unwrap();
+    HttpResponse::Ok().finish()
+}
+
+#[get("/claims")]
+async fn read_claims(identity: Identity) -> impl Responder {
+    web::Json(identity.claims::<Claims>().unwrap())
+}
+
+#[actix_web::test]
+async fn claims_round_trip_through_the_session() {
+    let app = test::init_service(
+        App::new()
+            .wrap(IdentityMiddleware::default())
+            .wrap(session_middleware())
+            .service(login_claims)
+            .service(read_claims),
+    )
+    .await;
+
+    let login =
+        test::call_service(&app, TestRequest::post().uri("/login-claims").to_request()).await;
+    assert!(login.status().is_success());
+    let cookie = login.response().cookies().next().unwrap().into_owned();
+
+    let req = TestRequest::get().uri("/claims").cookie(cookie).to_request();
+    let claims: Claims = test::call_and_read_body_json(&app, req).await;
+
+    assert_eq!(
+        claims,
+        Claims {
+            email: "user1@example.com".to_owned(),
+            roles: vec!["admin".to_owned()],
+        }
+    );
+}
+
+#[post("/login-weak")]
+async fn login_weak(req: HttpRequest) -> impl Responder {
+    Identity::login_with_level(&req, "user1".to_owned(), 0).unwrap();
+    HttpResponse::Ok().finish()
+}
+
+#[post("/login-strong")]
+async fn login_strong(req: HttpRequest) -> impl Responder {
+    Identity::login_with_level(&req, "user1".to_owned(), 2).unwrap();
+    HttpResponse::Ok().finish()
+}
+
+#[get("/high-assurance")]
+async fn high_assurance(user: RequireAuthLevel<2>) -> impl Responder {
+    user.id().unwrap()
+}
+
+#[actix_web::test]
+async fn require_auth_level_enforces_step_up() {
+    let app = test::init_service(
+        App::new()
+            .wrap(IdentityMiddleware::default())
+            .wrap(session_middleware())
+            .service(login_weak)
+            .service(login_strong)
+            .service(high_assurance),
+    )
+    .await;
+
+    // A level-0 login is authenticated but under-privileged: 403, not 401.
+    let weak = test::call_service(&app, TestRequest::post().uri("/login-weak").to_request()).await;
+    let weak_cookie = weak.response().cookies().next().unwrap().into_owned();
+    let req = TestRequest::get()
+        .uri("/high-assurance")
+        .cookie(weak_cookie)
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), actix_web::http::StatusCode::FORBIDDEN);
+
+    // An anonymous caller is unauthenticated: 401.
+    let req = TestRequest::get().uri("/high-assurance").to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+
+    // A level-2 login clears the bar.
+    let strong =
+        test::call_service(&app, TestRequest::post().uri("/login-strong").to_request()).await;
+    let strong_cookie = strong.response().cookies().next().unwrap().into_owned();
+    let req = TestRequest::get()
+        .uri("/high-assurance")
+        .cookie(strong_cookie)
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), actix_web::http::StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn on_identity_expired_fires_with_the_login_reason() {
+    let events: Arc<Mutex<Vec<(String, ExpiryReason)>>> = Arc::new(Mutex::new(Vec::new()));
+    let sink = Arc::clone(&events);
+
+    let app = test::init_service(
+        App::new()
+            .wrap(
+                IdentityMiddleware::builder()
+                    .login_deadline(Some(Duration::from_secs(1)))
+                    .on_identity_expired(move |id, reason| {
+                        sink.lock().unwrap().push((id.to_owned(), reason));
+                    })
+                    .build(),
+            )
+            .wrap(session_middleware())
+            .service(login)
+            .service(read_id),
+    )
+    .await;
+
+    let login = test::call_service(&app, TestRequest::post().uri("/login").to_request()).await;
+    let cookie = login.response().cookies().next().unwrap().into_owned();
+
+    // Let the absolute login deadline elapse, then touch a guarded route to trigger enforcement.
+    actix_web::rt::time::sleep(Duration::from_millis(1_100)).await;
+    let req = TestRequest::get().uri("/id").cookie(cookie).to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+
+    let events = events.lock().unwrap();
+    assert_eq!(events.as_slice(), [("user1".to_owned(), ExpiryReason::LoginDeadline)]);
+}
+
+#[post("/login-claims-and-level")]
+async fn login_claims_and_level(req: HttpRequest) -> impl Responder {
+    let claims = Claims {
+        email: "user1@example.com".to_owned(),
+        roles: vec!["admin".to_owned()],
+    };
+    let identity = Identity::login_with_claims(&req, "user1".to_owned(), &claims).unwrap();
+    identity.step_up(2).unwrap();
+    HttpResponse::Ok().finish()
+}
+
+#[post("/logout-plain")]
+async fn logout_plain(user: Identity) -> impl Responder {
+    user.logout();
+    HttpResponse::Ok().finish()
+}
+
+#[actix_web::test]
+async fn logout_clears_the_core_identity_and_the_extras() {
+    let app = test::init_service(
+        App::new()
+            .wrap(
+                IdentityMiddleware::builder()
+                    .logout_behaviour(actix_identity::config::LogoutBehaviour::DeleteIdentityKeys)
+                    .build(),
+            )
+            .wrap(session_middleware())
+            .service(login)
+            .service(login_claims_and_level)
+            .service(logout_plain)
+            .service(read_id)
+            .service(read_claims_checked),
+    )
+    .await;
+
+    let login = test::call_service(
+        &app,
+        TestRequest::post()
+            .uri("/login-claims-and-level")
+            .to_request(),
+    )
+    .await;
+    let cookie = login.response().cookies().next().unwrap().into_owned();
+
+    let req = TestRequest::post()
+        .uri("/logout-plain")
+        .cookie(cookie)
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), actix_web::http::StatusCode::OK);
+    // The cookie store keeps the session state in the cookie itself, so the logged-out state is the
+    // cookie set by the logout response.
+    let cookie = res.response().cookies().next().unwrap().into_owned();
+
+    // The core identity is forgotten through the policy, not just the feature extras.
+    let req = TestRequest::get().uri("/id").cookie(cookie.clone()).to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+
+    // The claims key is also dropped, not left behind for a future login to accidentally inherit:
+    // a plain `login` on the same session finds no claims.
+    let req = TestRequest::post().uri("/login").cookie(cookie).to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), actix_web::http::StatusCode::OK);
+    let cookie = res.response().cookies().next().unwrap().into_owned();
+
+    let req = TestRequest::get()
+        .uri("/claims-checked")
+        .cookie(cookie)
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+}
+
+#[post("/logout-relative")]
+async fn logout_relative(user: Identity) -> impl Responder {
+    user.logout_with_redirect("/goodbye".to_owned()).unwrap();
+    HttpResponse::Ok().finish()
+}
+
+#[post("/logout-external")]
+async fn logout_external(user: Identity) -> impl Responder {
+    user.logout_with_redirect("https://idp.example/logout".to_owned())
+        .unwrap();
+    HttpResponse::Ok().finish()
+}
+
+#[post("/logout-protocol-relative")]
+async fn logout_protocol_relative(user: Identity) -> impl Responder {
+    user.logout_with_redirect("//evil.example/phish".to_owned())
+        .unwrap();
+    HttpResponse::Ok().finish()
+}
+
+#[actix_web::test]
+async fn logout_with_redirect_is_applied_by_the_middleware() {
+    let app = test::init_service(
+        App::new()
+            .wrap(IdentityMiddleware::default())
+            .wrap(session_middleware())
+            .service(login)
+            .service(logout_relative)
+            .service(logout_external)
+            .service(logout_protocol_relative),
+    )
+    .await;
+
+    // A relative redirect is always allowed: the middleware rewrites the response to 303.
+    let res = test::call_service(&app, TestRequest::post().uri("/login").to_request()).await;
+    let cookie = res.response().cookies().next().unwrap().into_owned();
+    let req = TestRequest::post()
+        .uri("/logout-relative")
+        .cookie(cookie)
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), actix_web::http::StatusCode::SEE_OTHER);
+    assert_eq!(
+        res.headers()
+            .get(actix_web::http::header::LOCATION)
+            .unwrap(),
+        "/goodbye"
+    );
+
+    // An absolute redirect to a host that is not allow-listed falls back to the handler's response
+    // rather than leaking an open redirect.
+    let res = test::call_service(&app, TestRequest::post().uri("/login").to_request()).await;
+    let cookie = res.response().cookies().next().unwrap().into_owned();
+    let req = TestRequest::post()
+        .uri("/logout-external")
+        .cookie(cookie)
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), actix_web::http::StatusCode::OK);
+    assert!(res
+        .headers()
+        .get(actix_web::http::header::LOCATION)
+        .is_none());
+
+    // A protocol-relative URI ("//host/path") has no `Uri::host()` per RFC 3986, but a browser
+    // resolves a `Location: //evil.example/phish` response against the current scheme exactly like
+    // an absolute redirect — it must not slip through as if it were a same-origin relative path.
+    let res = test::call_service(&app, TestRequest::post().uri("/login").to_request()).await;
+    let cookie = res.response().cookies().next().unwrap().into_owned();
+    let req = TestRequest::post()
+        .uri("/logout-protocol-relative")
+        .cookie(cookie)
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), actix_web::http::StatusCode::OK);
+    assert!(res
+        .headers()
+        .get(actix_web::http::header::LOCATION)
+        .is_none());
+}
+
+#[actix_web::test]
+async fn claims_key_is_configurable() {
+    let app = test::init_service(
+        App::new()
+            .wrap(IdentityMiddleware::builder().claims_key("oidc-claims").build())
+            .wrap(session_middleware())
+            .service(login_claims)
+            .service(read_claims),
+    )
+    .await;
+
+    let login =
+        test::call_service(&app, TestRequest::post().uri("/login-claims").to_request()).await;
+    let cookie = login.response().cookies().next().unwrap().into_owned();
+
+    let req = TestRequest::get().uri("/claims").cookie(cookie).to_request();
+    let claims: Claims = test::call_and_read_body_json(&app, req).await;
+
+    assert_eq!(
+        claims,
+        Claims {
+            email: "user1@example.com".to_owned(),
+            roles: vec!["admin".to_owned()],
+        }
+    );
+}
+
+#[get("/claims-checked")]
+async fn read_claims_checked(
+    identity: Identity,
+) -> Result<impl Responder, actix_identity::error::GetIdentityError> {
+    Ok(web::Json(identity.claims::<Claims>()?))
+}
+
+#[actix_web::test]
+async fn deadline_expiry_clears_the_claims_and_auth_level_extras() {
+    let app = test::init_service(
+        App::new()
+            .wrap(
+                IdentityMiddleware::builder()
+                    .login_deadline(Some(Duration::from_secs(1)))
+                    .logout_behaviour(actix_identity::config::LogoutBehaviour::DeleteIdentityKeys)
+                    .build(),
+            )
+            .wrap(session_middleware())
+            .service(login)
+            .service(login_claims_and_level)
+            .service(read_id)
+            .service(read_claims_checked)
+            .service(high_assurance),
+    )
+    .await;
+
+    let login = test::call_service(
+        &app,
+        TestRequest::post()
+            .uri("/login-claims-and-level")
+            .to_request(),
+    )
+    .await;
+    let cookie = login.response().cookies().next().unwrap().into_owned();
+
+    // Let the absolute login deadline elapse, then touch a guarded route to trigger enforcement.
+    actix_web::rt::time::sleep(Duration::from_millis(1_100)).await;
+    let req = TestRequest::get()
+        .uri("/id")
+        .cookie(cookie.clone())
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+
+    // `DeleteIdentityKeys` leaves the rest of the session intact, so a plain `login` right after
+    // reuses it without purging. If the claims/auth-level keys had survived the deadline-triggered
+    // forget, this newly-logged-in identity would inherit the previous user's stale claims and
+    // auth level instead of having none at all.
+    let req = TestRequest::post()
+        .uri("/login")
+        .cookie(cookie.clone())
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), actix_web::http::StatusCode::OK);
+    let cookie = res.response().cookies().next().unwrap().into_owned();
+
+    let req = TestRequest::get()
+        .uri("/claims-checked")
+        .cookie(cookie.clone())
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+
+    let req = TestRequest::get()
+        .uri("/high-assurance")
+        .cookie(cookie)
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), actix_web::http::StatusCode::FORBIDDEN);
+}